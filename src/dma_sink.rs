@@ -0,0 +1,312 @@
+//! Backend-agnostic ping-pong chunking, shared between [`crate::spi_interface::SPIInterface`]'s
+//! esp-hal adapter ([`crate::spi_dma_displayinterface::EspHalSink`]) and the host-testable
+//! [`SpiBusDmaSink`]/[`LoopbackSink`] below. [`SpiBusDmaSink`] is a real, usable [`DmaSink`] for
+//! SPI peripherals without a DMA channel — this binary just doesn't have one wired up, since
+//! its one display is on a DMA-capable bus.
+#![allow(dead_code)]
+
+use embedded_hal::spi::SpiBus;
+
+/// A scratch buffer the chunker writes bytes into before handing it to [`DmaSink::submit`].
+pub trait ChunkBuf {
+    fn as_mut_slice(&mut self) -> &mut [u8];
+    fn set_length(&mut self, len: usize);
+}
+
+/// A transport with two alternating scratch buffers, so one can be filled by the CPU while
+/// the other is still in flight on the wire. This is the trait [`crate::spi_interface::SPIInterface`]
+/// is generic over, so it works equally over esp-hal's DMA-backed `SpiDma`
+/// ([`crate::spi_dma_displayinterface::EspHalSink`]), a plain blocking `embedded_hal::spi::SpiBus`
+/// ([`SpiBusDmaSink`]), or an in-memory [`LoopbackSink`].
+pub trait DmaSink {
+    /// A scratch buffer returned by [`Self::buffer`] and filled in by the chunker.
+    type Buf: ChunkBuf;
+    /// A transfer that has been submitted but may not yet be complete.
+    type InFlight;
+
+    /// Byte size [`chunk_and_send`] should split transfers into for this backend.
+    const CHUNK_SIZE: usize;
+
+    /// Borrows scratch buffer `which` (0 or 1).
+    fn buffer(&mut self, which: usize) -> Self::Buf;
+
+    /// Queues `buf` (already sized via [`ChunkBuf::set_length`]) for transfer.
+    fn submit(&mut self, buf: Self::Buf) -> Self::InFlight;
+
+    /// Non-blocking readiness check for `inflight`. Backends whose [`Self::submit`] already
+    /// blocks until the transfer is on the wire (as all the ones in this file do) can use the
+    /// default, which reports `true` unconditionally.
+    fn is_done(&self, _inflight: &Self::InFlight) -> bool {
+        true
+    }
+
+    /// Blocks until `inflight` completes.
+    fn reclaim(&mut self, inflight: Self::InFlight);
+
+    /// Attempts to send `data` directly instead of copying it through [`Self::buffer`], e.g.
+    /// by chaining DMA descriptors straight over the caller's memory. Returns `false` (the
+    /// default) when the backend has no such fast path; the caller then chunks `data` normally.
+    fn try_zero_copy_write(&mut self, _data: &mut [u8]) -> bool {
+        false
+    }
+}
+
+/// Splits `bytes` into `<= chunk_size`-byte pieces and alternates `sink`'s two scratch
+/// buffers, reclaiming a previously submitted buffer only once its slot is about to be reused.
+///
+/// `avg_data_len_hint` seeds the first chunk's size as `avg_data_len_hint % chunk_size` (a full
+/// chunk when that is zero), so a write of exactly the hinted length goes out as one short
+/// first chunk followed only by full-sized chunks. The final submitted transfer is returned rather than
+/// reclaimed here, so the caller can leave it in flight instead of blocking on it.
+pub fn chunk_and_send<S: DmaSink>(
+    sink: &mut S,
+    chunk_size: usize,
+    avg_data_len_hint: usize,
+    bytes: &mut dyn Iterator<Item = u8>,
+) -> Option<S::InFlight> {
+    let remainder = avg_data_len_hint % chunk_size;
+    // A hint that's an exact multiple of chunk_size would otherwise seed a zero-sized first
+    // chunk, which breaks out of the loop below after a single byte.
+    let mut desired_chunk_size = if remainder == 0 { chunk_size } else { remainder };
+
+    let mut current_buffer = 0;
+    let mut transfer: Option<S::InFlight> = None;
+
+    loop {
+        let mut buffer = sink.buffer(current_buffer);
+        let mut idx = 0;
+        loop {
+            match bytes.next() {
+                Some(b) => {
+                    buffer.as_mut_slice()[idx] = b;
+                    idx += 1;
+                }
+                None => break,
+            }
+
+            if idx >= usize::min(desired_chunk_size, chunk_size) {
+                break;
+            }
+        }
+        desired_chunk_size = chunk_size;
+
+        if idx == 0 {
+            break;
+        }
+
+        if let Some(prev) = transfer.take() {
+            sink.reclaim(prev);
+        }
+
+        buffer.set_length(idx);
+        transfer = Some(sink.submit(buffer));
+        current_buffer = (current_buffer + 1) % 2;
+    }
+
+    transfer
+}
+
+/// A plain, owned scratch buffer for the backends below, which (unlike the esp-hal DMA
+/// engine) don't need their buffers to live in a fixed static region.
+pub struct ByteChunkBuf<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for ByteChunkBuf<N> {
+    fn default() -> Self {
+        Self {
+            data: [0u8; N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> ByteChunkBuf<N> {
+    fn filled(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl<const N: usize> ChunkBuf for ByteChunkBuf<N> {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    fn set_length(&mut self, len: usize) {
+        self.len = len;
+    }
+}
+
+/// [`DmaSink`] over any blocking `embedded_hal::spi::SpiBus`, for SPI peripherals without a
+/// DMA channel. `SpiBus::write` already blocks until the bytes are on the wire, so `InFlight`
+/// carries nothing and [`DmaSink::reclaim`] is a no-op.
+pub struct SpiBusDmaSink<B, const CHUNK: usize> {
+    bus: B,
+}
+
+impl<B, const CHUNK: usize> SpiBusDmaSink<B, CHUNK>
+where
+    B: SpiBus,
+{
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    /// Recovers the underlying bus, e.g. to inspect it in a test.
+    pub fn into_inner(self) -> B {
+        self.bus
+    }
+}
+
+impl<B, const CHUNK: usize> DmaSink for SpiBusDmaSink<B, CHUNK>
+where
+    B: SpiBus,
+{
+    type Buf = ByteChunkBuf<CHUNK>;
+    type InFlight = ();
+
+    const CHUNK_SIZE: usize = CHUNK;
+
+    fn buffer(&mut self, _which: usize) -> Self::Buf {
+        ByteChunkBuf::default()
+    }
+
+    fn submit(&mut self, buf: Self::Buf) -> Self::InFlight {
+        self.bus.write(buf.filled()).ok();
+    }
+
+    fn reclaim(&mut self, _inflight: Self::InFlight) {}
+}
+
+/// In-memory [`DmaSink`] that records the exact byte stream it was asked to send instead of
+/// touching any hardware, so `set_pixels`' chunk-boundary and byte-order logic can be
+/// exercised on the host. Total capacity is fixed at `CAP` bytes (this crate is `no_std`);
+/// writes past that point are dropped and [`LoopbackSink::overflowed`] latches `true`.
+pub struct LoopbackSink<const CAP: usize, const CHUNK: usize> {
+    captured: [u8; CAP],
+    len: usize,
+    overflowed: bool,
+}
+
+impl<const CAP: usize, const CHUNK: usize> LoopbackSink<CAP, CHUNK> {
+    pub fn new() -> Self {
+        Self {
+            captured: [0u8; CAP],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// The bytes recorded so far, in submission order.
+    pub fn captured(&self) -> &[u8] {
+        &self.captured[..self.len]
+    }
+
+    /// `true` if a submitted buffer was truncated because it didn't fit in the remaining
+    /// capacity.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+}
+
+impl<const CAP: usize, const CHUNK: usize> Default for LoopbackSink<CAP, CHUNK> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CAP: usize, const CHUNK: usize> DmaSink for LoopbackSink<CAP, CHUNK> {
+    type Buf = ByteChunkBuf<CHUNK>;
+    type InFlight = ByteChunkBuf<CHUNK>;
+
+    const CHUNK_SIZE: usize = CHUNK;
+
+    fn buffer(&mut self, _which: usize) -> Self::Buf {
+        ByteChunkBuf::default()
+    }
+
+    fn submit(&mut self, buf: Self::Buf) -> Self::InFlight {
+        buf
+    }
+
+    fn reclaim(&mut self, buf: Self::InFlight) {
+        let data = buf.filled();
+        let remaining = CAP - self.len;
+        if data.len() > remaining {
+            self.overflowed = true;
+        }
+        let n = data.len().min(remaining);
+        self.captured[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::spi::{Error, ErrorKind, ErrorType};
+
+    #[test]
+    fn chunk_and_send_through_loopback_sink() {
+        let input: Vec<u8> = (0u8..200).collect();
+        let mut sink = LoopbackSink::<256, 64>::new();
+        let transfer = chunk_and_send(&mut sink, 64, 0, &mut input.iter().copied());
+        if let Some(t) = transfer {
+            sink.reclaim(t);
+        }
+
+        assert_eq!(sink.captured(), input.as_slice());
+        assert!(!sink.overflowed());
+    }
+
+    #[derive(Debug)]
+    struct MockError;
+
+    impl Error for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    #[derive(Default)]
+    struct MockBus {
+        written: Vec<u8>,
+    }
+
+    impl ErrorType for MockBus {
+        type Error = MockError;
+    }
+
+    impl SpiBus for MockBus {
+        fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.written.extend_from_slice(words);
+            Ok(())
+        }
+
+        fn transfer(&mut self, _read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+            self.write(write)
+        }
+
+        fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chunk_and_send_through_spi_bus_sink() {
+        let input: Vec<u8> = (0u8..200).collect();
+        let mut sink = SpiBusDmaSink::<MockBus, 64>::new(MockBus::default());
+        chunk_and_send(&mut sink, 64, 0, &mut input.iter().copied());
+
+        assert_eq!(sink.into_inner().written, input);
+    }
+}
@@ -1,10 +1,13 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 #![feature(type_alias_impl_trait)]
 
+#[cfg(not(test))]
 use embedded_graphics::pixelcolor::Rgb565;
+#[cfg(not(test))]
 use esp_backtrace as _;
 
+#[cfg(not(test))]
 use esp_hal::{
     delay::Delay,
     dma::{Dma, DmaPriority},
@@ -12,9 +15,13 @@ use esp_hal::{
     prelude::*,
     spi::master::Spi,
 };
+#[cfg(not(test))]
 use mipidsi::Builder;
 
+mod dma_sink;
+#[cfg(not(test))]
 mod spi_dma_displayinterface;
+mod spi_interface;
 
 const WIDTH: usize = 300;
 const HEIGHT: usize = 240;
@@ -48,6 +55,7 @@ const SINE_LUT: [u8; 512] = [
     61, 63, 65, 69, 71, 75, 77, 79, 83, 85, 90, 92, 94, 98, 100, 105, 107, 109, 114, 116, 120, 123,
 ];
 
+#[cfg(not(test))]
 #[entry]
 fn main() -> ! {
     esp_println::logger::init_logger_from_env();
@@ -173,4 +181,5 @@ fn main() -> ! {
     }
 }
 
+#[cfg(not(test))]
 static mut BUFFER: &mut [Rgb565; WIDTH * HEIGHT] = &mut [Rgb565::new(0, 0, 0); WIDTH * HEIGHT];
@@ -1,11 +1,13 @@
-//! DMA SPI interface for display drivers
+//! esp-hal DMA backend for [`crate::spi_interface::SPIInterface`]: owns the real `SpiDma`
+//! peripheral and static scratch buffers, and adds the zero-copy/full-duplex-read fast paths
+//! that only make sense against real DMA hardware.
 
 use core::cell::RefCell;
 use core::ptr::addr_of_mut;
 
-use byte_slice_cast::AsByteSlice;
-use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
-use esp_hal::dma::{DmaChannel, DmaDescriptor, DmaTxBuf, SpiPeripheral};
+use display_interface::DisplayError;
+use embedded_hal::digital::v2::OutputPin;
+use esp_hal::dma::{DmaChannel, DmaDescriptor, DmaRxBuf, DmaTxBuf, SpiPeripheral};
 use esp_hal::gpio::DummyPin;
 //use esp_hal::gpio::NoPin;
 use esp_hal::spi::master::InstanceDma;
@@ -13,30 +15,118 @@ use esp_hal::spi::master::SpiDmaTransfer;
 use esp_hal::spi::FullDuplexMode;
 use esp_hal::Blocking;
 
+use crate::dma_sink::{ChunkBuf, DmaSink};
+use crate::spi_interface::SPIInterface;
+
 const DMA_BUFFER_SIZE: usize = 4096;
 type SpiDma<'d, T, C> =
     esp_hal::spi::master::SpiDma<'d, T, C, esp_hal::spi::FullDuplexMode, esp_hal::Blocking>;
 
-/// SPI display interface.
-///
-/// This combines the SPI peripheral and a data/command as well as a chip-select pin
-pub struct SPIInterface<'d, DC, CS, T, C>
+/// Marker for output pins that are real, wired GPIOs rather than the [`DummyPin`] placeholder
+/// [`new_no_cs`] uses for CS. Gates [`SPIInterface::read`] at compile time.
+pub trait RealOutputPin: embedded_hal::digital::v2::OutputPin {}
+
+impl<'d> RealOutputPin for esp_hal::gpio::Output<'d> {}
+
+/// Owns the esp-hal DMA SPI peripheral and implements [`DmaSink`] so [`SPIInterface`] can drive
+/// it through the shared chunker, plus the zero-copy and full-duplex read fast paths that only
+/// make sense against real DMA hardware.
+pub struct EspHalSink<'d, T, C>
 where
-    DC: embedded_hal::digital::v2::OutputPin,
-    CS: embedded_hal::digital::v2::OutputPin,
     T: InstanceDma,
     C: DmaChannel,
     C::P: SpiPeripheral,
 {
-    avg_data_len_hint: usize,
     spi: RefCell<Option<SpiDma<'d, T, C>>>,
-    transfer: RefCell<Option<SpiDmaTransfer<'d, T, C, FullDuplexMode, Blocking, DmaTxBuf>>>,
-    dc: DC,
-    cs: Option<CS>,
+}
+
+impl<'d, T, C> EspHalSink<'d, T, C>
+where
+    T: InstanceDma,
+    C: DmaChannel,
+    C::P: SpiPeripheral,
+{
+    pub fn new(spi: SpiDma<'d, T, C>) -> Self {
+        Self {
+            spi: RefCell::new(Some(spi)),
+        }
+    }
+
+    fn take(&mut self) -> SpiDma<'d, T, C> {
+        self.spi.get_mut().take().unwrap()
+    }
+
+    fn put(&mut self, spi: SpiDma<'d, T, C>) {
+        self.spi.replace(Some(spi));
+    }
+}
+
+impl<'d, T, C> DmaSink for EspHalSink<'d, T, C>
+where
+    T: InstanceDma,
+    C: DmaChannel,
+    C::P: SpiPeripheral,
+{
+    type Buf = DmaTxBuf;
+    type InFlight = SpiDmaTransfer<'d, T, C, FullDuplexMode, Blocking, DmaTxBuf>;
+
+    const CHUNK_SIZE: usize = DMA_BUFFER_SIZE;
+
+    fn buffer(&mut self, which: usize) -> Self::Buf {
+        if which == 0 {
+            dma_buffer1()
+        } else {
+            dma_buffer2()
+        }
+    }
+
+    fn submit(&mut self, buf: Self::Buf) -> Self::InFlight {
+        self.take().dma_write(buf).unwrap()
+    }
+
+    fn is_done(&self, inflight: &Self::InFlight) -> bool {
+        inflight.is_done()
+    }
+
+    fn reclaim(&mut self, inflight: Self::InFlight) {
+        let (spi, _) = inflight.wait();
+        self.put(spi);
+    }
+
+    /// Chains `DmaDescriptor`s that point directly at successive windows of `data` instead of
+    /// `memcpy`-ing it into the static chunk buffers, avoiding a copy of the whole transfer.
+    /// Returns `false` without sending anything, so the caller chunks normally, when `data` is
+    /// too small to be worth a descriptor chain, doesn't live in DMA-capable RAM, or needs more
+    /// descriptors than the pool holds.
+    ///
+    /// Only `DataFormat::U16LE`/`DataFormat::U16BE` (a caller-owned `&mut [u16]` slice) can
+    /// reach this at all — `main.rs`'s render loop hands `mipidsi::Builder`/`set_pixels` a
+    /// `Rgb565` iterator, which `mipidsi` turns into a `U16BEIter`, not a slice, so this demo
+    /// never actually takes the zero-copy path; it only helps a caller that writes pixel data
+    /// from an owned `&mut [u16]` buffer directly.
+    fn try_zero_copy_write(&mut self, data: &mut [u8]) -> bool {
+        if data.len() <= DMA_BUFFER_SIZE || !is_dma_capable(data) {
+            return false;
+        }
+
+        let needed = data.len() / ZERO_COPY_CHUNK + 1;
+        let Some(descriptors) = zero_copy_descriptors(needed) else {
+            return false;
+        };
+
+        let Ok(send_buffer) = DmaTxBuf::new(descriptors, data) else {
+            return false;
+        };
+
+        let transfer = self.take().dma_write(send_buffer).unwrap();
+        let (spi, _) = transfer.wait();
+        self.put(spi);
+        true
+    }
 }
 
 #[allow(unused)]
-impl<'d, DC, CS, T, C> SPIInterface<'d, DC, CS, T, C>
+impl<'d, DC, CS, T, C> SPIInterface<DC, CS, EspHalSink<'d, T, C>>
 where
     DC: embedded_hal::digital::v2::OutputPin,
     CS: embedded_hal::digital::v2::OutputPin,
@@ -44,164 +134,78 @@ where
     C: DmaChannel,
     C::P: SpiPeripheral,
 {
+    /// Builds an interface directly over an esp-hal DMA-backed `SpiDma`.
     pub fn new(avg_data_len_hint: usize, spi: SpiDma<'d, T, C>, dc: DC, cs: CS) -> Self {
-        Self {
-            avg_data_len_hint,
-            spi: RefCell::new(Some(spi)),
-            transfer: RefCell::new(None),
-            dc,
-            cs: Some(cs),
-        }
+        Self::from_sink(avg_data_len_hint, EspHalSink::new(spi), dc, cs)
     }
+}
+
+#[allow(unused)]
+impl<'d, DC, CS, T, C> SPIInterface<DC, CS, EspHalSink<'d, T, C>>
+where
+    DC: embedded_hal::digital::v2::OutputPin,
+    CS: RealOutputPin,
+    T: InstanceDma,
+    C: DmaChannel,
+    C::P: SpiPeripheral,
+{
+    /// Reads back the response to `cmd` (e.g. RDDID `0x04`, RDID4 `0xD3`, RDDST `0x09`, or a
+    /// GRAM read-back command) into `out` over a full-duplex DMA transaction: assert CS, clock
+    /// out `cmd` with DC low, then clock `out.len() + 1` bytes with DC high while capturing
+    /// MISO, discarding the ILI9341's leading dummy byte.
+    ///
+    /// Needs real, full-duplex DMA hardware, so this is only available over [`EspHalSink`] —
+    /// not generic over every [`DmaSink`] backend.
+    pub fn read(&mut self, cmd: u8, out: &mut [u8]) -> Result<(), DisplayError> {
+        if out.len() + 1 > DMA_BUFFER_SIZE {
+            return Err(DisplayError::OutOfBoundsError);
+        }
+
+        self.flush_finish();
 
-    fn send_u8(&mut self, words: DataFormat<'_>) -> Result<(), DisplayError>
-    where
-        T: InstanceDma,
-        C: DmaChannel,
-        C::P: SpiPeripheral,
-    {
-        if let Some(transfer) = self.transfer.get_mut().take() {
-            let (reclaimed_spi, buffer) = transfer.wait();
-            self.spi.replace(Some(reclaimed_spi));
+        if let Some(cs) = self.cs_mut() {
+            cs.set_low().ok();
         }
 
-        match words {
-            DataFormat::U8(slice) => {
-                use byte_slice_cast::*;
-
-                let mut send_buffer = dma_buffer1();
-                send_buffer.as_mut_slice()[..slice.len()].copy_from_slice(slice.as_byte_slice());
-                send_buffer.set_length(slice.len());
-
-                self.single_transfer(send_buffer);
-            }
-            DataFormat::U16(slice) => {
-                use byte_slice_cast::*;
-
-                let mut send_buffer = dma_buffer1();
-                send_buffer.as_mut_slice()[..slice.len() * 2]
-                    .copy_from_slice(slice.as_byte_slice());
-                send_buffer.set_length(slice.len() * 2);
-
-                self.single_transfer(send_buffer);
-            }
-            DataFormat::U16LE(slice) => {
-                use byte_slice_cast::*;
-                for v in slice.as_mut() {
-                    *v = v.to_le();
-                }
-
-                let mut send_buffer = dma_buffer1();
-                send_buffer.as_mut_slice()[..slice.len() * 2]
-                    .copy_from_slice(slice.as_byte_slice());
-                send_buffer.set_length(slice.len() * 2);
-
-                self.single_transfer(send_buffer);
-            }
-            DataFormat::U16BE(slice) => {
-                use byte_slice_cast::*;
-                for v in slice.as_mut() {
-                    *v = v.to_be();
-                }
-
-                let mut send_buffer = dma_buffer1();
-                send_buffer.as_mut_slice()[..slice.len() * 2]
-                    .copy_from_slice(slice.as_byte_slice());
-                send_buffer.set_length(slice.len() * 2);
-
-                self.single_transfer(send_buffer);
-            }
-            DataFormat::U8Iter(iter) => {
-                self.iter_transfer(iter, |v| v.to_be_bytes());
-            }
-            DataFormat::U16LEIter(iter) => {
-                self.iter_transfer(iter, |v| v.to_le_bytes());
-            }
-            DataFormat::U16BEIter(iter) => {
-                self.iter_transfer(iter, |v| v.to_be_bytes());
-            }
-            _ => {
-                return Err(DisplayError::DataFormatNotImplemented);
-            }
+        // Command phase: DC low, single byte, MISO ignored.
+        self.dc_mut().set_low().ok();
+        let mut cmd_buffer = dma_buffer1();
+        cmd_buffer.as_mut_slice()[0] = cmd;
+        cmd_buffer.set_length(1);
+        let spi = self.sink_mut().take();
+        let (spi, _) = spi.dma_write(cmd_buffer).unwrap().wait();
+
+        // Data phase: DC high, clock out `n + 1` bytes while capturing MISO.
+        self.dc_mut().set_high().ok();
+        let n = out.len() + 1;
+        let mut tx_buffer = dma_buffer2();
+        tx_buffer.as_mut_slice()[..n].fill(0);
+        tx_buffer.set_length(n);
+        let mut rx_buffer = dma_rx_buffer();
+        rx_buffer.set_length(n);
+
+        let transfer = spi.dma_transfer(rx_buffer, tx_buffer).unwrap();
+        let (spi, (rx_buffer, _tx_buffer)) = transfer.wait();
+        self.sink_mut().put(spi);
+
+        // Discard the dummy byte the ILI9341 clocks out before the first valid byte.
+        out.copy_from_slice(&rx_buffer.as_slice()[1..n]);
+
+        if let Some(cs) = self.cs_mut() {
+            cs.set_high().ok();
         }
+
         Ok(())
     }
+}
 
-    fn single_transfer(&mut self, send_buffer: DmaTxBuf) {
-        let transfer = self
-            .spi
-            .get_mut()
-            .take()
-            .unwrap()
-            .dma_write(send_buffer)
-            .unwrap();
-        let (reclaimed_spi, _) = transfer.wait();
-        self.spi.replace(Some(reclaimed_spi));
+impl ChunkBuf for DmaTxBuf {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        DmaTxBuf::as_mut_slice(self)
     }
 
-    fn iter_transfer<WORD>(
-        &mut self,
-        iter: &mut dyn Iterator<Item = WORD>,
-        convert: fn(WORD) -> <WORD as num_traits::ToBytes>::Bytes,
-    ) where
-        WORD: num_traits::int::PrimInt + num_traits::ToBytes,
-    {
-        let mut desired_chunk_sized =
-            self.avg_data_len_hint - ((self.avg_data_len_hint / DMA_BUFFER_SIZE) * DMA_BUFFER_SIZE);
-        let mut spi = Some(self.spi.get_mut().take().unwrap());
-        let mut current_buffer = 0;
-        let mut transfer: Option<SpiDmaTransfer<'d, T, C, FullDuplexMode, Blocking, DmaTxBuf>> =
-            None;
-        loop {
-            let mut buffer = if current_buffer == 0 {
-                dma_buffer1()
-            } else {
-                dma_buffer2()
-            };
-            let mut idx = 0;
-            loop {
-                let b = iter.next();
-
-                match b {
-                    Some(b) => {
-                        let b = convert(b);
-                        let b = b.as_byte_slice();
-                        buffer.as_mut_slice()[idx + 0] = b[0];
-                        if b.len() == 2 {
-                            buffer.as_mut_slice()[idx + 1] = b[1];
-                        }
-                        idx += b.len();
-                    }
-                    None => break,
-                }
-
-                if idx >= usize::min(desired_chunk_sized, DMA_BUFFER_SIZE) {
-                    break;
-                }
-            }
-            desired_chunk_sized = DMA_BUFFER_SIZE;
-
-            if let Some(transfer) = transfer {
-                if idx > 0 {
-                    let (reclaimed_spi, relaimed_buffer) = transfer.wait();
-                    spi = Some(reclaimed_spi);
-                } else {
-                    // last transaction inflight
-                    self.transfer.replace(Some(transfer));
-                }
-            }
-
-            if idx > 0 {
-                buffer.set_length(idx);
-
-                let spi_instance = Option::take(&mut spi).unwrap();
-                transfer = Some(spi_instance.dma_write(buffer).unwrap());
-
-                current_buffer = (current_buffer + 1) % 2;
-            } else {
-                break;
-            }
-        }
+    fn set_length(&mut self, len: usize) {
+        DmaTxBuf::set_length(self, len)
     }
 }
 
@@ -229,69 +233,14 @@ pub fn new_no_cs<'d, DC, T, C>(
     avg_data_len_hint: usize,
     spi: SpiDma<'d, T, C>,
     dc: DC,
-) -> SPIInterface<'d, DC, DummyPin, T, C>
+) -> SPIInterface<DC, DummyPin, EspHalSink<'d, T, C>>
 where
     DC: embedded_hal::digital::v2::OutputPin,
     T: InstanceDma,
     C: DmaChannel,
     C::P: SpiPeripheral,
 {
-    SPIInterface {
-        avg_data_len_hint,
-        spi: RefCell::new(Some(spi)),
-        transfer: RefCell::new(None),
-        dc,
-        cs: Some(DummyPin::new()),
-    }
-}
-
-
-impl<'d, DC, CS, T, C> WriteOnlyDataCommand for SPIInterface<'d, DC, CS, T, C>
-where
-    DC: embedded_hal::digital::v2::OutputPin,
-    CS: embedded_hal::digital::v2::OutputPin,
-    T: InstanceDma,
-    C: DmaChannel,
-    C::P: SpiPeripheral,
-{
-    fn send_commands(&mut self, cmds: DataFormat<'_>) -> Result<(), DisplayError> {
-        // Assert chip select pin
-        if let Some(cs) = self.cs.as_mut() {
-            cs.set_low().ok();
-        }
-
-        // 1 = data, 0 = command
-        self.dc.set_low().ok();
-
-        // Send words over SPI
-        let res = self.send_u8(cmds);
-
-        // Deassert chip select pin
-        if let Some(cs) = self.cs.as_mut() {
-            cs.set_high().ok();
-        }
-        res
-    }
-
-    fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
-        // Assert chip select pin
-        if let Some(cs) = self.cs.as_mut() {
-            cs.set_low().ok();
-        }
-
-        // 1 = data, 0 = command
-        self.dc.set_high().ok();
-
-        // Send words over SPI
-        let res = self.send_u8(buf);
-
-        // Deassert chip select pin
-        if let Some(cs) = self.cs.as_mut() {
-            cs.set_high().ok();
-        }
-
-        res
-    }
+    SPIInterface::from_sink(avg_data_len_hint, EspHalSink::new(spi), dc, DummyPin::new())
 }
 
 fn dma_buffer1() -> DmaTxBuf {
@@ -311,3 +260,42 @@ fn dma_buffer2() -> DmaTxBuf {
 
     DmaTxBuf::new(tx_descriptors, tx_buffer).unwrap()
 }
+
+/// Upper bound on bytes covered by a single zero-copy descriptor, matching the headroom
+/// `dma_buffer1`/`dma_buffer2` already leave under the hardware's 4095-byte-per-descriptor
+/// limit.
+const ZERO_COPY_CHUNK: usize = 4092;
+
+/// Internal SRAM range the esp32s3 GDMA engine can read from directly. PSRAM, flash, and
+/// anything else outside this window has to go through the copy-and-ping-pong path instead.
+const DRAM_RANGE: core::ops::Range<usize> = 0x3FC8_8000..0x3FD0_0000;
+
+fn is_dma_capable(slice: &[u8]) -> bool {
+    let start = slice.as_ptr() as usize;
+    let end = start.wrapping_add(slice.len());
+    DRAM_RANGE.contains(&start) && end <= DRAM_RANGE.end
+}
+
+/// Hands out `count` descriptors from a static pool sized generously above what a full-screen
+/// frame needs, so zero-copy writes don't require their own per-call allocation in this
+/// `no_std` crate. Returns `None` if `count` exceeds the pool.
+fn zero_copy_descriptors(count: usize) -> Option<&'static mut [DmaDescriptor]> {
+    const POOL_SIZE: usize = 64;
+    static mut DESCRIPTORS: [DmaDescriptor; POOL_SIZE] = [DmaDescriptor::EMPTY; POOL_SIZE];
+
+    if count > POOL_SIZE {
+        return None;
+    }
+
+    let pool = unsafe { &mut *addr_of_mut!(DESCRIPTORS) };
+    Some(&mut pool[..count])
+}
+
+fn dma_rx_buffer() -> DmaRxBuf {
+    static mut BUFFER: [u8; DMA_BUFFER_SIZE] = [0u8; DMA_BUFFER_SIZE];
+    let rx_buffer = unsafe { &mut *addr_of_mut!(BUFFER) };
+    static mut RX_DESCRIPTORS: [DmaDescriptor; 8 * 3] = [DmaDescriptor::EMPTY; 8 * 3];
+    let rx_descriptors = unsafe { &mut *addr_of_mut!(RX_DESCRIPTORS) };
+
+    DmaRxBuf::new(rx_descriptors, rx_buffer).unwrap()
+}
@@ -0,0 +1,303 @@
+//! Backend-agnostic [`SPIInterface`]: generic over any [`DmaSink`], so the chunked write path
+//! doesn't need real DMA hardware to build or test. `spi_dma_displayinterface` supplies the
+//! esp-hal DMA backend plus the zero-copy/read fast paths that only make sense against real
+//! hardware.
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context as TaskContext, Poll};
+
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+
+use crate::dma_sink::{self, DmaSink};
+
+/// SPI display interface, generic over the [`DmaSink`] backend that actually moves bytes.
+///
+/// This combines a backend and a data/command as well as a chip-select pin. Use
+/// [`Self::from_sink`] to build one over any [`DmaSink`] (e.g.
+/// [`crate::dma_sink::SpiBusDmaSink`] for a plain blocking `SpiBus` with no DMA channel);
+/// `spi_dma_displayinterface` additionally provides `SPIInterface::new`/`new_no_cs` over its
+/// esp-hal DMA backend.
+pub struct SPIInterface<DC, CS, S: DmaSink> {
+    avg_data_len_hint: usize,
+    sink: RefCell<S>,
+    transfer: RefCell<Option<S::InFlight>>,
+    dc: DC,
+    cs: Option<CS>,
+}
+
+#[allow(unused)]
+impl<DC, CS, S> SPIInterface<DC, CS, S>
+where
+    DC: embedded_hal::digital::v2::OutputPin,
+    CS: embedded_hal::digital::v2::OutputPin,
+    S: DmaSink,
+{
+    /// Builds an interface directly over a [`DmaSink`] backend.
+    pub fn from_sink(avg_data_len_hint: usize, sink: S, dc: DC, cs: CS) -> Self {
+        Self {
+            avg_data_len_hint,
+            sink: RefCell::new(sink),
+            transfer: RefCell::new(None),
+            dc,
+            cs: Some(cs),
+        }
+    }
+
+    /// Queues `buf` for transfer and returns immediately instead of blocking until it
+    /// completes. Reclaim the backend via [`Self::poll`], [`Self::flush_finish`], or by
+    /// awaiting the returned [`InFlight`].
+    pub fn flush_begin(&mut self, buf: S::Buf) -> InFlight<'_, DC, CS, S> {
+        // Only one transfer can be in flight against a given backend at a time.
+        self.flush_finish();
+
+        let transfer = self.sink.get_mut().submit(buf);
+        self.transfer.replace(Some(transfer));
+
+        InFlight { interface: self }
+    }
+
+    /// Non-blocking status check of the in-flight transfer, if any. Returns `true` once it has
+    /// completed (reclaiming the backend) or if nothing is in flight.
+    pub fn poll(&mut self) -> bool {
+        let done = match self.transfer.get_mut() {
+            Some(t) => self.sink.get_mut().is_done(t),
+            None => return true,
+        };
+        if !done {
+            return false;
+        }
+        let transfer = self.transfer.get_mut().take().unwrap();
+        self.sink.get_mut().reclaim(transfer);
+        true
+    }
+
+    /// Blocks until the in-flight transfer (if any) completes and reclaims the backend.
+    pub fn flush_finish(&mut self) {
+        if let Some(transfer) = self.transfer.get_mut().take() {
+            self.sink.get_mut().reclaim(transfer);
+        }
+    }
+
+    /// Direct access to the backend, for backend-specific extensions (e.g.
+    /// [`crate::spi_dma_displayinterface::SPIInterface::read`]) that need to drive it beyond
+    /// what [`DmaSink`] exposes.
+    pub(crate) fn sink_mut(&mut self) -> &mut S {
+        self.sink.get_mut()
+    }
+
+    /// Direct access to the DC pin, for backend-specific extensions.
+    pub(crate) fn dc_mut(&mut self) -> &mut DC {
+        &mut self.dc
+    }
+
+    /// Direct access to the CS pin, for backend-specific extensions.
+    pub(crate) fn cs_mut(&mut self) -> Option<&mut CS> {
+        self.cs.as_mut()
+    }
+
+    fn send_u8(&mut self, words: DataFormat<'_>) -> Result<(), DisplayError> {
+        self.flush_finish();
+
+        match words {
+            DataFormat::U8(slice) => {
+                self.chunked_transfer(&mut slice.iter().copied());
+            }
+            DataFormat::U16(slice) => {
+                // Native byte order — matches the baseline's `as_byte_slice()` cast this
+                // replaced; `DataFormat::U16` carries no byte-order contract of its own, so
+                // this must stay native-endian rather than picking LE/BE here.
+                self.chunked_transfer(&mut slice.iter().flat_map(|v| v.to_ne_bytes()));
+            }
+            DataFormat::U16LE(slice) => {
+                use byte_slice_cast::*;
+                for v in slice.as_mut() {
+                    *v = v.to_le();
+                }
+
+                if !self
+                    .sink
+                    .get_mut()
+                    .try_zero_copy_write(slice.as_mut_byte_slice())
+                {
+                    self.chunked_transfer(&mut slice.iter().flat_map(|v| v.to_ne_bytes()));
+                }
+            }
+            DataFormat::U16BE(slice) => {
+                use byte_slice_cast::*;
+                for v in slice.as_mut() {
+                    *v = v.to_be();
+                }
+
+                if !self
+                    .sink
+                    .get_mut()
+                    .try_zero_copy_write(slice.as_mut_byte_slice())
+                {
+                    self.chunked_transfer(&mut slice.iter().flat_map(|v| v.to_ne_bytes()));
+                }
+            }
+            DataFormat::U8Iter(iter) => {
+                self.chunked_transfer(iter);
+            }
+            DataFormat::U16LEIter(iter) => {
+                self.chunked_transfer(&mut iter.flat_map(|v| v.to_le_bytes()));
+            }
+            DataFormat::U16BEIter(iter) => {
+                self.chunked_transfer(&mut iter.flat_map(|v| v.to_be_bytes()));
+            }
+            _ => {
+                return Err(DisplayError::DataFormatNotImplemented);
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits `bytes` into `<= S::CHUNK_SIZE` chunks and alternates the backend's two scratch
+    /// buffers via the shared chunker in [`dma_sink::chunk_and_send`].
+    fn chunked_transfer(&mut self, bytes: &mut dyn Iterator<Item = u8>) {
+        let avg_data_len_hint = self.avg_data_len_hint;
+        let sink = self.sink.get_mut();
+        let transfer = dma_sink::chunk_and_send(sink, S::CHUNK_SIZE, avg_data_len_hint, bytes);
+
+        if let Some(transfer) = transfer {
+            // Leave the last chunk's transfer in flight instead of blocking on it here; it is
+            // reclaimed lazily at the top of the next `send_u8`/`flush_begin` call, or by
+            // `flush_finish`/`poll`.
+            self.transfer.replace(Some(transfer));
+        }
+    }
+}
+
+impl<DC, CS, S> WriteOnlyDataCommand for SPIInterface<DC, CS, S>
+where
+    DC: embedded_hal::digital::v2::OutputPin,
+    CS: embedded_hal::digital::v2::OutputPin,
+    S: DmaSink,
+{
+    fn send_commands(&mut self, cmds: DataFormat<'_>) -> Result<(), DisplayError> {
+        // Assert chip select pin
+        if let Some(cs) = self.cs.as_mut() {
+            cs.set_low().ok();
+        }
+
+        // 1 = data, 0 = command
+        self.dc.set_low().ok();
+
+        // Send words over SPI
+        let res = self.send_u8(cmds);
+
+        // Deassert chip select pin
+        if let Some(cs) = self.cs.as_mut() {
+            cs.set_high().ok();
+        }
+        res
+    }
+
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+        // Assert chip select pin
+        if let Some(cs) = self.cs.as_mut() {
+            cs.set_low().ok();
+        }
+
+        // 1 = data, 0 = command
+        self.dc.set_high().ok();
+
+        // Send words over SPI
+        let res = self.send_u8(buf);
+
+        // Deassert chip select pin
+        if let Some(cs) = self.cs.as_mut() {
+            cs.set_high().ok();
+        }
+
+        res
+    }
+}
+
+/// A handle to the DMA transfer started by [`SPIInterface::flush_begin`]. Poll it with
+/// [`InFlight::poll`], block on it with [`InFlight::wait`], or `.await` it directly.
+///
+/// This is a cooperative poll API, not interrupt-driven: nothing registers the DMA channel's
+/// completion interrupt, so awaiting it just re-polls and re-arms the waker until the
+/// transfer's done. Also not yet wired into `main.rs`'s render loop — `flush_begin`/`poll` are
+/// only called from here.
+pub struct InFlight<'a, DC, CS, S: DmaSink> {
+    interface: &'a mut SPIInterface<DC, CS, S>,
+}
+
+#[allow(unused)]
+impl<'a, DC, CS, S> InFlight<'a, DC, CS, S>
+where
+    DC: embedded_hal::digital::v2::OutputPin,
+    CS: embedded_hal::digital::v2::OutputPin,
+    S: DmaSink,
+{
+    /// Non-blocking check for completion. See [`SPIInterface::poll`].
+    pub fn poll(&mut self) -> bool {
+        self.interface.poll()
+    }
+
+    /// Blocks until the transfer completes. See [`SPIInterface::flush_finish`].
+    pub fn wait(self) {
+        self.interface.flush_finish();
+    }
+}
+
+impl<'a, DC, CS, S> Future for InFlight<'a, DC, CS, S>
+where
+    DC: embedded_hal::digital::v2::OutputPin,
+    CS: embedded_hal::digital::v2::OutputPin,
+    S: DmaSink,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.interface.poll() {
+            Poll::Ready(())
+        } else {
+            // No interrupt wiring yet, so just ask to be polled again.
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dma_sink::LoopbackSink;
+    use embedded_hal::digital::v2::OutputPin;
+
+    #[derive(Default)]
+    struct MockPin {
+        high: bool,
+    }
+
+    impl OutputPin for MockPin {
+        type Error = core::convert::Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.high = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.high = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spi_interface_over_loopback_sink_sends_data() {
+        let sink = LoopbackSink::<256, 64>::new();
+        let mut di = SPIInterface::from_sink(0, sink, MockPin::default(), MockPin::default());
+
+        di.send_data(DataFormat::U8(&[1, 2, 3, 4, 5])).unwrap();
+        di.flush_finish();
+
+        assert_eq!(di.sink.get_mut().captured(), &[1, 2, 3, 4, 5]);
+    }
+}